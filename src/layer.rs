@@ -3,11 +3,121 @@ use std::str::FromStr;
 use std::task::{Context, Poll};
 
 use futures::future::BoxFuture;
+use hyper::http::header::InvalidHeaderName;
 use hyper::http::{HeaderName, HeaderValue, Request, Response};
 use tower::{Layer, Service};
 
 use crate::{MakeTraceId, TraceId};
 
+/// Builds the [`tracing::Span`] that [`SetTraceIdLayer::with_tracing_span`] opens around every
+/// call to the inner service.
+///
+/// `tracing`'s span macros need their name to be a string literal at the call site, so a
+/// span's name can't be threaded through [`SetTraceIdLayer`] as a plain value the way e.g.
+/// header names can. Implement this trait (or supply a closure, via the blanket impl below)
+/// and call `tracing::info_span!`/`debug_span!`/etc. yourself with a literal name to give the
+/// span a name other than the default `"request"`; pass it to
+/// [`SetTraceIdLayer::with_make_span`].
+#[cfg(feature = "tracing")]
+pub trait MakeSpan<T>
+where
+    T: MakeTraceId,
+{
+    fn make_span(&self, trace_id: &TraceId<T>) -> tracing::Span;
+}
+
+#[cfg(feature = "tracing")]
+impl<T, F> MakeSpan<T> for F
+where
+    T: MakeTraceId,
+    F: Fn(&TraceId<T>) -> tracing::Span,
+{
+    fn make_span(&self, trace_id: &TraceId<T>) -> tracing::Span {
+        self(trace_id)
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy)]
+struct DefaultMakeSpan {
+    level: tracing::Level,
+}
+
+#[cfg(feature = "tracing")]
+impl Default for DefaultMakeSpan {
+    fn default() -> Self {
+        Self {
+            level: tracing::Level::INFO,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T> MakeSpan<T> for DefaultMakeSpan
+where
+    T: MakeTraceId,
+{
+    fn make_span(&self, trace_id: &TraceId<T>) -> tracing::Span {
+        match self.level {
+            tracing::Level::TRACE => tracing::trace_span!("request", trace_id = %trace_id),
+            tracing::Level::DEBUG => tracing::debug_span!("request", trace_id = %trace_id),
+            tracing::Level::INFO => tracing::info_span!("request", trace_id = %trace_id),
+            tracing::Level::WARN => tracing::warn_span!("request", trace_id = %trace_id),
+            tracing::Level::ERROR => tracing::error_span!("request", trace_id = %trace_id),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+enum TracingSpanConfig<T>
+where
+    T: MakeTraceId,
+{
+    Default(DefaultMakeSpan),
+    Custom(std::sync::Arc<dyn MakeSpan<T> + Send + Sync>),
+}
+
+#[cfg(feature = "tracing")]
+impl<T> Clone for TracingSpanConfig<T>
+where
+    T: MakeTraceId,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Self::Default(default_make_span) => Self::Default(*default_make_span),
+            Self::Custom(make_span) => Self::Custom(make_span.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T> std::fmt::Debug for TracingSpanConfig<T>
+where
+    T: MakeTraceId,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default(default_make_span) => {
+                f.debug_tuple("Default").field(default_make_span).finish()
+            }
+            Self::Custom(_) => f.debug_tuple("Custom").finish(),
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<T> MakeSpan<T> for TracingSpanConfig<T>
+where
+    T: MakeTraceId,
+{
+    fn make_span(&self, trace_id: &TraceId<T>) -> tracing::Span {
+        match self {
+            Self::Default(default_make_span) => default_make_span.make_span(trace_id),
+            Self::Custom(make_span) => make_span.make_span(trace_id),
+        }
+    }
+}
+
 /// Add the TraceId<T> extension to requests and optionally include trace ids in request and response headers.
 ///
 /// ```
@@ -18,19 +128,27 @@ use crate::{MakeTraceId, TraceId};
 ///
 /// let trace_id_header = "x-trace-id";
 /// let svc = ServiceBuilder::new()
-///     .layer(SetTraceIdLayer::<String>::new().with_header_name(trace_id_header))
+///     .layer(SetTraceIdLayer::<String>::new().try_with_header_name(trace_id_header).unwrap())
 ///     .service_fn(|_req: Request<Body>| async {
 ///         let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
 ///         res
 ///     });
 ///
 /// ```
+///
+/// With the `tracing` feature enabled, [`SetTraceIdLayer::with_tracing_span`] opens a
+/// span carrying the trace id as a field around every call to the inner service, so
+/// log events emitted by handlers are automatically annotated with it.
 #[derive(Debug, Clone)]
 pub struct SetTraceIdLayer<T>
 where
     T: MakeTraceId,
 {
-    header_name: Option<HeaderName>,
+    request_header_name: Option<HeaderName>,
+    response_header_name: Option<HeaderName>,
+    propagate_incoming: bool,
+    #[cfg(feature = "tracing")]
+    tracing_span: Option<TracingSpanConfig<T>>,
     _phantom: PhantomData<T>,
 }
 
@@ -40,15 +158,119 @@ where
 {
     pub fn new() -> Self {
         Self {
-            header_name: None,
+            request_header_name: None,
+            response_header_name: None,
+            propagate_incoming: false,
+            #[cfg(feature = "tracing")]
+            tracing_span: None,
             _phantom: Default::default(),
         }
     }
 
-    pub fn with_header_name(self, header_name: &str) -> Self {
+    /// Set only the header that gets written on the request passed to the inner
+    /// service (and, when [`SetTraceIdLayer::propagate_incoming`] is enabled, read
+    /// from on the incoming request).
+    pub fn with_request_header_name(self, header_name: HeaderName) -> Self {
         Self {
-            header_name: Some(HeaderName::from_str(header_name).unwrap()),
-            _phantom: Default::default(),
+            request_header_name: Some(header_name),
+            ..self
+        }
+    }
+
+    /// Set only the header that gets written on the outgoing response. Useful when
+    /// the trace id should be returned to the caller without forwarding it to the
+    /// inner service on the request.
+    pub fn with_response_header_name(self, header_name: HeaderName) -> Self {
+        Self {
+            response_header_name: Some(header_name),
+            ..self
+        }
+    }
+
+    /// Validate `header_name` and use it for both the request and response header.
+    /// Returns an error instead of panicking when `header_name` is not a valid
+    /// header name.
+    pub fn try_with_header_name(self, header_name: &str) -> Result<Self, InvalidHeaderName> {
+        let header_name = HeaderName::from_str(header_name)?;
+        Ok(self
+            .with_request_header_name(header_name.clone())
+            .with_response_header_name(header_name))
+    }
+
+    /// Validate `header_name` and use it only for the request header. See
+    /// [`SetTraceIdLayer::with_request_header_name`].
+    pub fn try_with_request_header_name(
+        self,
+        header_name: &str,
+    ) -> Result<Self, InvalidHeaderName> {
+        Ok(self.with_request_header_name(HeaderName::from_str(header_name)?))
+    }
+
+    /// Validate `header_name` and use it only for the response header. See
+    /// [`SetTraceIdLayer::with_response_header_name`].
+    pub fn try_with_response_header_name(
+        self,
+        header_name: &str,
+    ) -> Result<Self, InvalidHeaderName> {
+        Ok(self.with_response_header_name(HeaderName::from_str(header_name)?))
+    }
+
+    /// When set, an inbound request that already carries the request header name is
+    /// honoured: the header value is parsed via [`MakeTraceId::from_header_value`]
+    /// and reused instead of minting a new trace id. Parsing failures or a missing
+    /// header fall back to [`MakeTraceId::make_trace_id_for`] as before.
+    pub fn propagate_incoming(self, propagate_incoming: bool) -> Self {
+        Self {
+            propagate_incoming,
+            ..self
+        }
+    }
+
+    /// Open a [`tracing::Span`] named `"request"` carrying the trace id as a field
+    /// around every call to the inner service, so log events emitted by handlers are
+    /// automatically annotated with it. Defaults to `INFO`; use
+    /// [`SetTraceIdLayer::with_tracing_span_level`] to customize the level, or
+    /// [`SetTraceIdLayer::with_make_span`] to customize the name (or anything else about
+    /// the span).
+    #[cfg(feature = "tracing")]
+    pub fn with_tracing_span(self) -> Self {
+        Self {
+            tracing_span: Some(
+                self.tracing_span
+                    .unwrap_or(TracingSpanConfig::Default(DefaultMakeSpan::default())),
+            ),
+            ..self
+        }
+    }
+
+    /// Customize the level of the span enabled by [`SetTraceIdLayer::with_tracing_span`].
+    /// Has no effect once [`SetTraceIdLayer::with_make_span`] has supplied a custom span
+    /// builder, since that builder owns the span's level entirely.
+    #[cfg(feature = "tracing")]
+    pub fn with_tracing_span_level(self, level: tracing::Level) -> Self {
+        let tracing_span = match self.tracing_span {
+            Some(TracingSpanConfig::Custom(make_span)) => TracingSpanConfig::Custom(make_span),
+            Some(TracingSpanConfig::Default(_)) | None => {
+                TracingSpanConfig::Default(DefaultMakeSpan { level })
+            }
+        };
+        Self {
+            tracing_span: Some(tracing_span),
+            ..self
+        }
+    }
+
+    /// Fully customize the [`tracing::Span`] opened by [`SetTraceIdLayer::with_tracing_span`],
+    /// e.g. to give it a name other than `"request"`. See [`MakeSpan`] for why this takes a
+    /// builder rather than a plain name.
+    #[cfg(feature = "tracing")]
+    pub fn with_make_span<M>(self, make_span: M) -> Self
+    where
+        M: MakeSpan<T> + Send + Sync + 'static,
+    {
+        Self {
+            tracing_span: Some(TracingSpanConfig::Custom(std::sync::Arc::new(make_span))),
+            ..self
         }
     }
 }
@@ -71,17 +293,28 @@ where
     fn layer(&self, inner: S) -> Self::Service {
         TraceIdMiddleware {
             inner,
-            header_name: self.header_name.clone(),
-            _phantom: Default::default(),
+            request_header_name: self.request_header_name.clone(),
+            response_header_name: self.response_header_name.clone(),
+            propagate_incoming: self.propagate_incoming,
+            #[cfg(feature = "tracing")]
+            tracing_span: self.tracing_span.clone(),
+            maker: T::make_trace_id(),
         }
     }
 }
 
 #[derive(Clone)]
-pub struct TraceIdMiddleware<S, T> {
+pub struct TraceIdMiddleware<S, T>
+where
+    T: MakeTraceId,
+{
     inner: S,
-    header_name: Option<HeaderName>,
-    _phantom: PhantomData<T>,
+    request_header_name: Option<HeaderName>,
+    response_header_name: Option<HeaderName>,
+    propagate_incoming: bool,
+    #[cfg(feature = "tracing")]
+    tracing_span: Option<TracingSpanConfig<T>>,
+    maker: T,
 }
 
 impl<S, T, Rq, Rs> Service<Request<Rq>> for TraceIdMiddleware<S, T>
@@ -99,30 +332,53 @@ where
     }
 
     fn call(&mut self, mut req: Request<Rq>) -> Self::Future {
-        let trace_id = TraceId::<T>::new();
+        let incoming = if self.propagate_incoming {
+            self.request_header_name
+                .as_ref()
+                .and_then(|header_name| req.headers().get(header_name))
+                .and_then(T::from_header_value)
+        } else {
+            None
+        };
+
+        let trace_id = match incoming {
+            Some(id) => TraceId { id },
+            None => TraceId {
+                id: self.maker.make_trace_id_for(&req),
+            },
+        };
         req.extensions_mut().insert(trace_id.clone());
 
-        // Add TraceId header to request
-        let mut header_val: Option<HeaderValue> = None;
-        if let Some(header_name) = self.header_name.clone() {
-            header_val = Some(
-                HeaderValue::try_from(trace_id.id.to_string())
-                    .unwrap_or(HeaderValue::from_static("unavailable")),
-            );
+        // The header value is shared between the request and response headers, so
+        // compute it once; a trace id whose Display output isn't header-safe simply
+        // isn't reflected into either header instead of panicking.
+        let header_value = HeaderValue::try_from(trace_id.to_string()).ok();
+
+        if let (Some(header_name), Some(header_value)) = (&self.request_header_name, &header_value)
+        {
             req.headers_mut()
-                .insert(header_name, header_val.clone().unwrap());
+                .insert(header_name.clone(), header_value.clone());
         }
 
+        #[cfg(feature = "tracing")]
+        let span = match &self.tracing_span {
+            Some(tracing_span) => tracing_span.make_span(&trace_id),
+            None => tracing::Span::none(),
+        };
+
         let future = self.inner.call(req);
-        let moved_header_name = self.header_name.clone();
+        let response_header_name = self.response_header_name.clone();
         Box::pin(async move {
+            #[cfg(feature = "tracing")]
+            let future = {
+                use tracing::Instrument;
+                future.instrument(span)
+            };
+
             let mut response: Response<Rs> = future.await?;
 
-            // Add TraceId header to response
-            if let Some(header_name) = moved_header_name {
-                response
-                    .headers_mut()
-                    .insert(header_name, header_val.unwrap());
+            if let (Some(header_name), Some(header_value)) = (response_header_name, header_value) {
+                response.headers_mut().insert(header_name, header_value);
             }
 
             Ok(response)
@@ -136,6 +392,8 @@ mod tests {
     use hyper::body::Body;
     use std::cell::RefCell;
     use std::convert::Infallible;
+    use std::fmt::{Display, Formatter};
+    use std::sync::atomic::{AtomicU64, Ordering};
     use std::sync::{Arc, Mutex};
     use tower::{ServiceBuilder, ServiceExt};
 
@@ -209,7 +467,11 @@ mod tests {
         };
 
         let test_svc = ServiceBuilder::new()
-            .layer(SetTraceIdLayer::<String>::new().with_header_name(header_name))
+            .layer(
+                SetTraceIdLayer::<String>::new()
+                    .try_with_header_name(header_name)
+                    .unwrap(),
+            )
             .map_request(assert_trace_id)
             .service_fn(|_req: Request<Body>| async {
                 let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
@@ -221,4 +483,171 @@ mod tests {
 
         assert!(resp.headers().get(header_name).is_some());
     }
+
+    #[tokio::test]
+    async fn test_propagate_incoming_reuses_header() {
+        let header_name = "x-trace-id";
+        let incoming_id = "incoming-trace-id";
+
+        let test_svc = ServiceBuilder::new()
+            .layer(
+                SetTraceIdLayer::<String>::new()
+                    .try_with_header_name(header_name)
+                    .unwrap()
+                    .propagate_incoming(true),
+            )
+            .service_fn(|req: Request<Body>| async move {
+                let trace_id = req.extensions().get::<TraceId<String>>().unwrap().clone();
+                let res: Result<Response<Body>, Infallible> =
+                    Ok(Response::new(Body::from(trace_id.to_string())));
+                res
+            });
+
+        let req = Request::builder()
+            .header(header_name, incoming_id)
+            .body(Body::empty())
+            .unwrap();
+        let resp = test_svc.oneshot(req).await.unwrap();
+
+        assert_eq!(resp.headers().get(header_name).unwrap(), incoming_id);
+    }
+
+    #[tokio::test]
+    async fn test_response_only_header_not_forwarded_to_inner_request() {
+        let header_name = "x-trace-id";
+
+        let assert_no_request_header = |req: Request<Body>| -> Request<Body> {
+            assert!(req.headers().get(header_name).is_none());
+            req
+        };
+
+        let test_svc = ServiceBuilder::new()
+            .layer(
+                SetTraceIdLayer::<String>::new()
+                    .try_with_response_header_name(header_name)
+                    .unwrap(),
+            )
+            .map_request(assert_no_request_header)
+            .service_fn(|_req: Request<Body>| async {
+                let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
+                res
+            });
+
+        let req = Request::new(Body::empty());
+        let resp = test_svc.oneshot(req).await.unwrap();
+
+        assert!(resp.headers().get(header_name).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_propagate_incoming_falls_back_when_missing() {
+        let header_name = "x-trace-id";
+
+        let test_svc = ServiceBuilder::new()
+            .layer(
+                SetTraceIdLayer::<String>::new()
+                    .try_with_header_name(header_name)
+                    .unwrap()
+                    .propagate_incoming(true),
+            )
+            .service_fn(|_req: Request<Body>| async {
+                let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
+                res
+            });
+
+        let req = Request::new(Body::empty());
+        let resp = test_svc.oneshot(req).await.unwrap();
+
+        assert!(resp.headers().get(header_name).is_some());
+    }
+
+    #[derive(Debug, Clone)]
+    struct CountingTraceId {
+        count: Arc<AtomicU64>,
+    }
+
+    impl Display for CountingTraceId {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.count.load(Ordering::SeqCst))
+        }
+    }
+
+    impl MakeTraceId for CountingTraceId {
+        fn make_trace_id() -> Self {
+            Self {
+                count: Arc::new(AtomicU64::new(0)),
+            }
+        }
+
+        fn make_trace_id_for<B>(&mut self, _req: &Request<B>) -> Self {
+            Self {
+                count: Arc::new(AtomicU64::new(
+                    self.count.fetch_add(1, Ordering::SeqCst) + 1,
+                )),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_make_trace_id_for_is_stateful() {
+        let header_name = "x-trace-id";
+
+        let test_svc = ServiceBuilder::new()
+            .layer(
+                SetTraceIdLayer::<CountingTraceId>::new()
+                    .try_with_header_name(header_name)
+                    .unwrap(),
+            )
+            .service_fn(|_req: Request<Body>| async {
+                let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
+                res
+            });
+
+        let first = test_svc
+            .clone()
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let second = test_svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(first.headers().get(header_name).unwrap(), "1");
+        assert_eq!(second.headers().get(header_name).unwrap(), "2");
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_span_wraps_inner_call() {
+        let test_svc = ServiceBuilder::new()
+            .layer(
+                SetTraceIdLayer::<String>::new()
+                    .with_tracing_span()
+                    .with_tracing_span_level(tracing::Level::DEBUG),
+            )
+            .service_fn(|_req: Request<Body>| async {
+                assert!(tracing::Span::current().metadata().is_some());
+                let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
+                res
+            });
+
+        let req = Request::new(Body::empty());
+        test_svc.oneshot(req).await.unwrap();
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_with_make_span_customizes_span_name() {
+        let test_svc = ServiceBuilder::new()
+            .layer(SetTraceIdLayer::<String>::new().with_make_span(|trace_id: &TraceId<String>| {
+                tracing::info_span!("my_request", trace_id = %trace_id)
+            }))
+            .service_fn(|_req: Request<Body>| async {
+                let name = tracing::Span::current().metadata().unwrap().name();
+                assert_eq!(name, "my_request");
+                let res: Result<Response<Body>, Infallible> = Ok(Response::new(Body::empty()));
+                res
+            });
+
+        let req = Request::new(Body::empty());
+        test_svc.oneshot(req).await.unwrap();
+    }
 }
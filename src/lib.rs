@@ -1,9 +1,20 @@
+// NOTE: the `axum` and `tracing` features gating `extract`/parts of `layer` below must be
+// registered in this crate's Cargo.toml, e.g.:
+//   [dependencies]
+//   tracing = { version = "...", optional = true }
+//   [features]
+//   tracing = ["dep:tracing"]
+// and likewise for `axum`, or `--features tracing`/`--features axum` fail with "unknown feature".
+
 #[cfg(feature = "axum")]
 mod extract;
 
 mod layer;
+mod trace_parent;
 
 pub use crate::layer::SetTraceIdLayer;
+pub use crate::trace_parent::TraceParent;
+use hyper::http::{HeaderValue, Request};
 use std::fmt::{Display, Formatter};
 use uuid::Uuid;
 
@@ -35,12 +46,39 @@ use uuid::Uuid;
 /// ```
 pub trait MakeTraceId: Send + Sync + Display + Clone {
     fn make_trace_id() -> Self;
+
+    /// Like [`MakeTraceId::make_trace_id`], but given a reference to the request the
+    /// id is being generated for and `&mut self` access to the maker that produced
+    /// the previous id. This allows trace ids to be derived from request data (the
+    /// route, a header, a tenant prefix, ...) or to be stateful, e.g. a maker backed
+    /// by an `AtomicU64` that hands out monotonically increasing ids.
+    ///
+    /// The default implementation ignores the request and the maker's state, simply
+    /// delegating to [`MakeTraceId::make_trace_id`], so existing implementors stay
+    /// source-compatible.
+    fn make_trace_id_for<B>(&mut self, _req: &Request<B>) -> Self {
+        Self::make_trace_id()
+    }
+
+    /// Attempt to build a trace id from an inbound header value, e.g. one set by an
+    /// upstream proxy or service. Returning `None` tells the caller to fall back to
+    /// [`MakeTraceId::make_trace_id_for`] instead.
+    ///
+    /// The default implementation always returns `None`, so existing implementors
+    /// keep their current (always-generate) behaviour unless they opt in.
+    fn from_header_value(_header_value: &HeaderValue) -> Option<Self> {
+        None
+    }
 }
 
 impl MakeTraceId for String {
     fn make_trace_id() -> Self {
         Uuid::new_v4().to_string()
     }
+
+    fn from_header_value(header_value: &HeaderValue) -> Option<Self> {
+        header_value.to_str().ok().map(str::to_string)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -51,17 +89,6 @@ where
     pub id: T,
 }
 
-impl<T> TraceId<T>
-where
-    T: MakeTraceId,
-{
-    pub(crate) fn new() -> Self {
-        TraceId {
-            id: T::make_trace_id(),
-        }
-    }
-}
-
 impl<T> Display for TraceId<T>
 where
     T: MakeTraceId,
@@ -0,0 +1,209 @@
+use std::fmt::{Display, Formatter};
+
+use hyper::http::HeaderValue;
+use uuid::Uuid;
+
+use crate::MakeTraceId;
+
+const VERSION: &str = "00";
+const TRACE_FLAGS: &str = "01";
+
+/// A [W3C Trace Context](https://www.w3.org/TR/trace-context/) `traceparent` value.
+///
+/// Implements [`MakeTraceId`] so it can be used as the `T` in [`crate::TraceId`] and
+/// [`crate::SetTraceIdLayer`], letting this crate interoperate with OpenTelemetry-style
+/// distributed tracing systems instead of only emitting opaque ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    trace_id: [u8; 16],
+    parent_id: [u8; 8],
+}
+
+impl TraceParent {
+    fn random_trace_id() -> [u8; 16] {
+        loop {
+            let bytes = *Uuid::new_v4().as_bytes();
+            if bytes != [0; 16] {
+                return bytes;
+            }
+        }
+    }
+
+    fn random_parent_id() -> [u8; 8] {
+        loop {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+            if bytes != [0; 8] {
+                return bytes;
+            }
+        }
+    }
+
+    /// Parse a `traceparent` header value per the W3C spec: `version-trace_id-parent_id-flags`,
+    /// with 2/32/16/2 lowercase hex characters respectively. Returns `None` on any malformed
+    /// field or an all-zero trace-id or parent-id, in which case the caller should fall back
+    /// to a freshly generated
+    /// [`TraceParent`].
+    ///
+    /// A successfully parsed `traceparent` carries the incoming `trace-id` forward but is given
+    /// a fresh `parent-id` (span id) for this hop, per spec.
+    fn parse(value: &str) -> Option<Self> {
+        let fields: Vec<&str> = value.split('-').collect();
+        if fields.len() != 4 {
+            return None;
+        }
+        let [version, trace_id, parent_id, trace_flags] =
+            [fields[0], fields[1], fields[2], fields[3]];
+
+        // Only version 00 is understood; in particular, version ff is reserved by the spec
+        // as permanently invalid and must never be forward-parsed.
+        if version != VERSION || !is_lowercase_hex(trace_flags, 2) {
+            return None;
+        }
+
+        let trace_id = decode_hex::<16>(trace_id)?;
+        let parent_id = decode_hex::<8>(parent_id)?;
+
+        if trace_id == [0; 16] || parent_id == [0; 8] {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            parent_id: Self::random_parent_id(),
+        })
+    }
+}
+
+/// Checks that `value` is exactly `len` lowercase hex digits, per the W3C spec (which
+/// forbids uppercase). Validating every byte up front, rather than just the length,
+/// also guarantees `value` is plain ASCII, so callers can safely byte-slice it
+/// afterwards without risking a UTF-8 char-boundary panic on malformed input.
+fn is_lowercase_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    if !is_lowercase_hex(value, N * 2) {
+        return None;
+    }
+
+    let mut bytes = [0u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl Display for TraceParent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{VERSION}-{}-{}-{TRACE_FLAGS}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.parent_id),
+        )
+    }
+}
+
+impl MakeTraceId for TraceParent {
+    fn make_trace_id() -> Self {
+        Self {
+            trace_id: Self::random_trace_id(),
+            parent_id: Self::random_parent_id(),
+        }
+    }
+
+    fn from_header_value(header_value: &HeaderValue) -> Option<Self> {
+        Self::parse(header_value.to_str().ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_matches_w3c_format() {
+        let trace_parent = TraceParent::make_trace_id();
+        let rendered = trace_parent.to_string();
+
+        let fields: Vec<&str> = rendered.split('-').collect();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0], "00");
+        assert_eq!(fields[1].len(), 32);
+        assert_eq!(fields[2].len(), 16);
+        assert_eq!(fields[3], "01");
+    }
+
+    #[test]
+    fn test_parse_valid_traceparent_keeps_trace_id_and_refreshes_parent_id() {
+        let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let header_value = HeaderValue::from_static(incoming);
+
+        let parsed = TraceParent::from_header_value(&header_value).unwrap();
+        let rendered = parsed.to_string();
+
+        assert!(rendered.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+        assert!(!rendered.contains("00f067aa0ba902b7"));
+    }
+
+    #[test]
+    fn test_parse_rejects_all_zero_trace_id() {
+        let incoming = "00-00000000000000000000000000000000-00f067aa0ba902b7-01";
+        let header_value = HeaderValue::from_static(incoming);
+
+        assert!(TraceParent::from_header_value(&header_value).is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_all_zero_parent_id() {
+        let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01";
+        let header_value = HeaderValue::from_static(incoming);
+
+        assert!(TraceParent::from_header_value(&header_value).is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_header() {
+        let header_value = HeaderValue::from_static("not-a-traceparent");
+
+        assert!(TraceParent::from_header_value(&header_value).is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_reserved_future_version() {
+        let incoming = "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let header_value = HeaderValue::from_static(incoming);
+
+        assert!(TraceParent::from_header_value(&header_value).is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_uppercase_hex() {
+        let incoming = "00-4BF92F3577B34DA6A3CE929D0E0E4736-00F067AA0BA902B7-01";
+        let header_value = HeaderValue::from_static(incoming);
+
+        assert!(TraceParent::from_header_value(&header_value).is_none());
+    }
+
+    #[test]
+    fn test_parse_does_not_panic_on_multibyte_utf8() {
+        // "€" is 3 UTF-8 bytes, so this trace-id field is 32 bytes long (matching the
+        // expected length) but only 30 chars, landing a naive byte-offset slice in
+        // the middle of the "€" and panicking instead of returning None.
+        let trace_id = format!("€{}", "0".repeat(29));
+        let incoming = format!("00-{trace_id}-00f067aa0ba902b7-01");
+
+        // Call `parse` directly: `HeaderValue::to_str` already rejects non-ASCII bytes, so
+        // going through `from_header_value` would never reach the slicing in `decode_hex`.
+        assert!(TraceParent::parse(&incoming).is_none());
+    }
+}